@@ -7,16 +7,35 @@ use near_sdk::{
     PanicOnDefault, Promise, PromiseOrValue,
 };
 
+#[derive(BorshSerialize, BorshStorageKey)]
+#[borsh(crate = "near_sdk::borsh")]
+enum StorageKey {
+    Debates,
+    Votes,
+    VotesIndex,
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DebateStatus {
+    Open,
+    Closed,
+    Finalized,
+}
+
 #[near]
 pub struct Debate {
     topic: String,
     creator: AccountId,
     created_at: u64,
-    figure_1_name: String,
-    figure_1_image_url: String,
-    figure_2_name: String,
-    figure_2_image_url: String,
+    figures: Vec<(String, String)>,
+    figure_stakes: Vec<U128>,
+    abstain_stake: U128,
     debate_dialogue: Vec<(String, String, String)>,
+    vote_start_ms: u64,
+    vote_end_ms: u64,
+    status: DebateStatus,
+    winner: Option<u8>,
 }
 
 #[near]
@@ -25,6 +44,8 @@ pub struct Vote {
     voter: AccountId,
     voted_at: u64,
     choice: u8,
+    stake: U128,
+    claimed: bool,
 }
 
 #[near_bindgen]
@@ -36,6 +57,7 @@ pub struct Contract {
     next_debate_id: u64,
     votes: UnorderedMap<u64, Vote>,
     next_vote_id: u64,
+    votes_index: LookupMap<(u64, AccountId), u64>,
 }
 
 #[near_bindgen]
@@ -48,6 +70,77 @@ impl Contract {
             next_debate_id: 1,
             votes: UnorderedMap::new(StorageKey::Votes),
             next_vote_id: 1,
+            votes_index: LookupMap::new(StorageKey::VotesIndex),
+        }
+    }
+
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        #[derive(BorshDeserialize)]
+        #[borsh(crate = "near_sdk::borsh")]
+        struct OldDebate {
+            topic: String,
+            creator: AccountId,
+            created_at: u64,
+            figure_1_name: String,
+            figure_1_image_url: String,
+            figure_2_name: String,
+            figure_2_image_url: String,
+            debate_dialogue: Vec<(String, String, String)>,
+            figure_1_stake: U128,
+            figure_2_stake: U128,
+            vote_start_ms: u64,
+            vote_end_ms: u64,
+            status: DebateStatus,
+            winner: Option<u8>,
+        }
+
+        #[derive(BorshDeserialize)]
+        #[borsh(crate = "near_sdk::borsh")]
+        struct OldContract {
+            owner_id: AccountId,
+            debates: UnorderedMap<u64, OldDebate>,
+            next_debate_id: u64,
+            votes: UnorderedMap<u64, Vote>,
+            next_vote_id: u64,
+            votes_index: LookupMap<(u64, AccountId), u64>,
+        }
+
+        let old: OldContract = env::state_read().expect("Failed to read old state");
+
+        // The rigid figure_1/figure_2 pair becomes a two-element `figures`
+        // list, choice indices shift to 1-based-into-that-list, and 0 is now
+        // reserved for abstain (no prior votes used it).
+        let mut debates: UnorderedMap<u64, Debate> = UnorderedMap::new(StorageKey::Debates);
+        for (debate_id, old_debate) in old.debates.iter() {
+            debates.insert(
+                &debate_id,
+                &Debate {
+                    topic: old_debate.topic,
+                    creator: old_debate.creator,
+                    created_at: old_debate.created_at,
+                    figures: vec![
+                        (old_debate.figure_1_name, old_debate.figure_1_image_url),
+                        (old_debate.figure_2_name, old_debate.figure_2_image_url),
+                    ],
+                    figure_stakes: vec![old_debate.figure_1_stake, old_debate.figure_2_stake],
+                    abstain_stake: U128(0),
+                    debate_dialogue: old_debate.debate_dialogue,
+                    vote_start_ms: old_debate.vote_start_ms,
+                    vote_end_ms: old_debate.vote_end_ms,
+                    status: old_debate.status,
+                    winner: old_debate.winner,
+                },
+            );
+        }
+
+        Self {
+            owner_id: old.owner_id,
+            debates,
+            next_debate_id: old.next_debate_id,
+            votes: old.votes,
+            next_vote_id: old.next_vote_id,
+            votes_index: old.votes_index,
         }
     }
 }
@@ -57,26 +150,45 @@ impl Contract {
     pub fn create_debate(
         &mut self,
         topic: String,
-        figure_1_name: String,
-        figure_1_image_url: String,
-        figure_2_name: String,
-        figure_2_image_url: String,
+        figures: Vec<(String, String)>,
         debate_dialogue: Vec<(String, String, String)>,
+        vote_duration_ms: u64,
     ) -> u64 {
+        if figures.is_empty() {
+            env::panic_str("A debate needs at least one figure!");
+        }
+
+        if figures.len() > u8::MAX as usize {
+            env::panic_str("A debate cannot have more than 255 figures!");
+        }
+
+        if vote_duration_ms == 0 {
+            env::panic_str("Vote duration must be greater than zero!");
+        }
+
         let debate_id = self.next_debate_id;
         self.next_debate_id += 1;
 
         let creator = env::signer_account_id();
+        let created_at = env::block_timestamp_ms();
+        let vote_end_ms = created_at
+            .checked_add(vote_duration_ms)
+            .unwrap_or_else(|| env::panic_str("Vote duration is too large!"));
+
+        let figure_stakes = vec![U128(0); figures.len()];
 
         let debate = Debate {
             topic,
             creator,
-            created_at: env::block_timestamp_ms(),
-            figure_1_name,
-            figure_1_image_url,
-            figure_2_name,
-            figure_2_image_url,
+            created_at,
+            figures,
+            figure_stakes,
+            abstain_stake: U128(0),
             debate_dialogue,
+            vote_start_ms: created_at,
+            vote_end_ms,
+            status: DebateStatus::Open,
+            winner: None,
         };
 
         self.debates.insert(&debate_id, &debate);
@@ -85,23 +197,24 @@ impl Contract {
         debate_id
     }
 
+    #[payable]
     pub fn vote_debate(&mut self, debate_id: u64, choice: u8) -> u64 {
         let voter = env::signer_account_id();
 
-        if self.debates.get(&debate_id).is_none() {
-            env::panic_str("Debate not found!");
-        }
+        let mut debate = match self.debates.get(&debate_id) {
+            Some(debate) => debate,
+            None => env::panic_str("Debate not found!"),
+        };
 
-        if choice != 1 && choice != 2 {
-            env::panic_str("Invalid choice! Choose 1 or 2.");
-        }
+        self.assert_valid_choice(&debate, choice);
+        self.assert_vote_open(&debate);
 
-        for (_, existing_vote) in self.votes.iter() {
-            if existing_vote.debate_id == debate_id && existing_vote.voter == voter {
-                env::panic_str("You have already voted in this debate!");
-            }
+        if self.votes_index.get(&(debate_id, voter.clone())).is_some() {
+            env::panic_str("You have already voted in this debate!");
         }
 
+        let stake = env::attached_deposit().as_yoctonear();
+
         let vote_id = self.next_vote_id;
         self.next_vote_id += 1;
 
@@ -110,12 +223,21 @@ impl Contract {
             voter: voter.clone(),
             voted_at: env::block_timestamp_ms(),
             choice,
+            stake: U128(stake),
+            claimed: false,
         };
 
         self.votes.insert(&vote_id, &vote);
+        self.votes_index
+            .insert(&(debate_id, voter.clone()), &vote_id);
+
+        self.add_stake(&mut debate, choice, stake);
+        self.debates.insert(&debate_id, &debate);
+
         log!(
-            "{} voted for choice {} in debate {}",
+            "{} staked {} yoctoNEAR on choice {} in debate {}",
             voter,
+            stake,
             choice,
             debate_id
         );
@@ -123,6 +245,146 @@ impl Contract {
         vote_id
     }
 
+    pub fn change_vote(&mut self, debate_id: u64, new_choice: u8) {
+        let voter = env::signer_account_id();
+
+        let vote_id = match self.votes_index.get(&(debate_id, voter.clone())) {
+            Some(vote_id) => vote_id,
+            None => env::panic_str("You have not voted in this debate yet!"),
+        };
+
+        let mut vote = self
+            .votes
+            .get(&vote_id)
+            .unwrap_or_else(|| env::panic_str("Vote not found!"));
+
+        if vote.claimed {
+            env::panic_str("Vote already refunded, cannot change it!");
+        }
+
+        if vote.choice == new_choice {
+            env::panic_str("You already voted for this choice!");
+        }
+
+        let mut debate = self
+            .debates
+            .get(&debate_id)
+            .unwrap_or_else(|| env::panic_str("Debate not found!"));
+
+        self.assert_valid_choice(&debate, new_choice);
+        self.assert_vote_open(&debate);
+
+        self.remove_stake(&mut debate, vote.choice, vote.stake.0);
+        self.add_stake(&mut debate, new_choice, vote.stake.0);
+
+        vote.choice = new_choice;
+        vote.voted_at = env::block_timestamp_ms();
+
+        self.votes.insert(&vote_id, &vote);
+        self.debates.insert(&debate_id, &debate);
+
+        log!(
+            "{} switched their vote to choice {} in debate {}",
+            voter,
+            new_choice,
+            debate_id
+        );
+    }
+
+    pub fn claim_refund(&mut self, vote_id: u64) -> Promise {
+        let voter = env::signer_account_id();
+
+        let mut vote = match self.votes.get(&vote_id) {
+            Some(vote) => vote,
+            None => env::panic_str("Vote not found!"),
+        };
+
+        if vote.voter != voter {
+            env::panic_str("Only the voter can claim this refund!");
+        }
+
+        if vote.claimed {
+            env::panic_str("Refund already claimed!");
+        }
+
+        let mut debate = match self.debates.get(&vote.debate_id) {
+            Some(debate) => debate,
+            None => env::panic_str("Debate not found!"),
+        };
+
+        if !self.is_losing_choice(&debate, vote.choice) {
+            env::panic_str("Only votes on the losing side can be refunded!");
+        }
+
+        vote.claimed = true;
+        self.votes.insert(&vote_id, &vote);
+
+        self.remove_stake(&mut debate, vote.choice, vote.stake.0);
+        self.debates.insert(&vote.debate_id, &debate);
+
+        Promise::new(voter).transfer(NearToken::from_yoctonear(vote.stake.0))
+    }
+
+    /// Pays a winning-side voter their stake back plus their pro-rata share
+    /// of every losing figure's pool, once the debate has been finalized.
+    pub fn claim_winnings(&mut self, vote_id: u64) -> Promise {
+        let voter = env::signer_account_id();
+
+        let mut vote = match self.votes.get(&vote_id) {
+            Some(vote) => vote,
+            None => env::panic_str("Vote not found!"),
+        };
+
+        if vote.voter != voter {
+            env::panic_str("Only the voter can claim these winnings!");
+        }
+
+        if vote.claimed {
+            env::panic_str("Winnings already claimed!");
+        }
+
+        let debate = match self.debates.get(&vote.debate_id) {
+            Some(debate) => debate,
+            None => env::panic_str("Debate not found!"),
+        };
+
+        if debate.status != DebateStatus::Finalized {
+            env::panic_str("Debate has not been finalized yet!");
+        }
+
+        if debate.winner != Some(vote.choice) {
+            env::panic_str("Only votes on the winning figure can claim winnings!");
+        }
+
+        let payout = self.winnings_payout(&debate, &vote);
+
+        vote.claimed = true;
+        self.votes.insert(&vote_id, &vote);
+
+        Promise::new(voter).transfer(NearToken::from_yoctonear(payout))
+    }
+
+    pub fn finalize_debate(&mut self, debate_id: u64) {
+        let mut debate = self
+            .debates
+            .get(&debate_id)
+            .unwrap_or_else(|| env::panic_str("Debate not found!"));
+
+        if debate.status == DebateStatus::Finalized {
+            env::panic_str("Debate already finalized!");
+        }
+
+        if env::block_timestamp_ms() < debate.vote_end_ms {
+            env::panic_str("Voting has not ended yet!");
+        }
+
+        debate.winner = self.winning_figure(&debate);
+        debate.status = DebateStatus::Finalized;
+
+        self.debates.insert(&debate_id, &debate);
+        log!("Debate {} finalized", debate_id);
+    }
+
     pub fn get_debates(
         &self,
     ) -> Vec<(
@@ -130,40 +392,30 @@ impl Contract {
         String,
         AccountId,
         u64,
-        String,
-        String,
-        String,
-        String,
+        Vec<(String, String)>,
+        Vec<U128>,
+        U128,
         u64,
         u64,
+        DebateStatus,
+        Option<u8>,
     )> {
         let mut debate_list = Vec::new();
 
         for (debate_id, debate) in self.debates.iter() {
-            let mut figure_1_votes = 0;
-            let mut figure_2_votes = 0;
-
-            for (_, vote) in self.votes.iter() {
-                if vote.debate_id == debate_id {
-                    if vote.choice == 1 {
-                        figure_1_votes += 1;
-                    } else if vote.choice == 2 {
-                        figure_2_votes += 1;
-                    }
-                }
-            }
-
+            let status = self.effective_status(&debate);
             debate_list.push((
                 debate_id,
                 debate.topic.clone(),
                 debate.creator.clone(),
                 debate.created_at.clone(),
-                debate.figure_1_name.clone(),
-                debate.figure_1_image_url.clone(),
-                debate.figure_2_name.clone(),
-                debate.figure_2_image_url.clone(),
-                figure_1_votes,
-                figure_2_votes,
+                debate.figures.clone(),
+                debate.figure_stakes.clone(),
+                debate.abstain_stake,
+                debate.vote_start_ms,
+                debate.vote_end_ms,
+                status,
+                debate.winner,
             ));
         }
 
@@ -178,40 +430,30 @@ impl Contract {
         String,
         AccountId,
         u64,
-        String,
-        String,
-        String,
-        String,
+        Vec<(String, String)>,
+        Vec<U128>,
+        U128,
         Vec<(String, String, String)>,
         u64,
         u64,
+        DebateStatus,
+        Option<u8>,
     )> {
         self.debates.get(&debate_id).map(|debate| {
-            let mut figure_1_votes = 0;
-            let mut figure_2_votes = 0;
-
-            for (_, vote) in self.votes.iter() {
-                if vote.debate_id == debate_id {
-                    if vote.choice == 1 {
-                        figure_1_votes += 1;
-                    } else if vote.choice == 2 {
-                        figure_2_votes += 1;
-                    }
-                }
-            }
-
+            let status = self.effective_status(&debate);
             (
                 debate_id,
                 debate.topic.clone(),
                 debate.creator.clone(),
                 debate.created_at.clone(),
-                debate.figure_1_name.clone(),
-                debate.figure_1_image_url.clone(),
-                debate.figure_2_name.clone(),
-                debate.figure_2_image_url.clone(),
+                debate.figures.clone(),
+                debate.figure_stakes.clone(),
+                debate.abstain_stake,
                 debate.debate_dialogue.clone(),
-                figure_1_votes,
-                figure_2_votes,
+                debate.vote_start_ms,
+                debate.vote_end_ms,
+                status,
+                debate.winner,
             )
         })
     }
@@ -219,12 +461,259 @@ impl Contract {
     pub fn get_user_vote_in_debate(&self, debate_id: u64) -> Option<(u8, u64)> {
         let voter = env::signer_account_id();
 
-        for (_, vote) in self.votes.iter() {
-            if vote.debate_id == debate_id && vote.voter == voter {
-                return Some((vote.choice, vote.voted_at));
+        let vote_id = self.votes_index.get(&(debate_id, voter))?;
+        self.votes
+            .get(&vote_id)
+            .map(|vote| (vote.choice, vote.voted_at))
+    }
+}
+
+impl Contract {
+    const ABSTAIN_CHOICE: u8 = 0;
+
+    fn assert_valid_choice(&self, debate: &Debate, choice: u8) {
+        if choice == Self::ABSTAIN_CHOICE {
+            return;
+        }
+
+        if choice as usize > debate.figures.len() {
+            env::panic_str("Invalid choice! Choose a listed figure or 0 to abstain.");
+        }
+    }
+
+    fn assert_vote_open(&self, debate: &Debate) {
+        if debate.status == DebateStatus::Finalized {
+            env::panic_str("Voting is closed for this debate!");
+        }
+
+        let now = env::block_timestamp_ms();
+        if now < debate.vote_start_ms || now >= debate.vote_end_ms {
+            env::panic_str("Voting is not open for this debate!");
+        }
+    }
+
+    fn effective_status(&self, debate: &Debate) -> DebateStatus {
+        if debate.status == DebateStatus::Finalized {
+            return DebateStatus::Finalized;
+        }
+
+        if env::block_timestamp_ms() >= debate.vote_end_ms {
+            DebateStatus::Closed
+        } else {
+            DebateStatus::Open
+        }
+    }
+
+    fn add_stake(&self, debate: &mut Debate, choice: u8, amount: u128) {
+        if choice == Self::ABSTAIN_CHOICE {
+            debate.abstain_stake = U128(debate.abstain_stake.0 + amount);
+        } else {
+            let stake = &mut debate.figure_stakes[choice as usize - 1];
+            *stake = U128(stake.0 + amount);
+        }
+    }
+
+    fn remove_stake(&self, debate: &mut Debate, choice: u8, amount: u128) {
+        if choice == Self::ABSTAIN_CHOICE {
+            debate.abstain_stake = U128(debate.abstain_stake.0 - amount);
+        } else {
+            let stake = &mut debate.figure_stakes[choice as usize - 1];
+            *stake = U128(stake.0 - amount);
+        }
+    }
+
+    /// Abstain never wins; among the remaining figures the strict max wins,
+    /// a tie for first place resolves to no winner.
+    fn winning_figure(&self, debate: &Debate) -> Option<u8> {
+        let mut winner: Option<(usize, u128)> = None;
+        let mut tied = false;
+
+        for (idx, stake) in debate.figure_stakes.iter().enumerate() {
+            match winner {
+                Some((_, best)) if stake.0 > best => {
+                    winner = Some((idx, stake.0));
+                    tied = false;
+                }
+                Some((_, best)) if stake.0 == best => tied = true,
+                None => winner = Some((idx, stake.0)),
+                _ => {}
             }
         }
 
-        None // Jika user belum vote, kembalikan None
+        match winner {
+            Some((idx, stake)) if stake > 0 && !tied => Some(idx as u8 + 1),
+            _ => None,
+        }
+    }
+
+    /// A vote can be refunded unless it backed the (sole, strict) winner.
+    /// Once finalized the winner is frozen on the debate; before that, the
+    /// live tally is used so un-finalized debates can still be refunded.
+    /// A tie for first place has no winner, so every figure is refundable.
+    fn is_losing_choice(&self, debate: &Debate, choice: u8) -> bool {
+        if choice == Self::ABSTAIN_CHOICE {
+            return true;
+        }
+
+        let winner = if debate.status == DebateStatus::Finalized {
+            debate.winner
+        } else {
+            self.winning_figure(debate)
+        };
+
+        winner != Some(choice)
+    }
+
+    /// The winner's stake back plus its pro-rata share of every losing
+    /// figure's pool. `checked_mul` guards against the stake/pool product
+    /// overflowing u128 for very large pools; it panics rather than wrap.
+    fn winnings_payout(&self, debate: &Debate, vote: &Vote) -> u128 {
+        let winner_idx = vote.choice as usize - 1;
+        let winner_stake = debate.figure_stakes[winner_idx].0;
+        let losing_pool: u128 = debate
+            .figure_stakes
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| *idx != winner_idx)
+            .map(|(_, stake)| stake.0)
+            .sum();
+
+        let share = vote
+            .stake
+            .0
+            .checked_mul(losing_pool)
+            .unwrap_or_else(|| env::panic_str("Payout calculation overflowed!"))
+            / winner_stake;
+
+        vote.stake.0 + share
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    fn owner() -> AccountId {
+        "owner.near".parse().unwrap()
+    }
+
+    fn alice() -> AccountId {
+        "alice.near".parse().unwrap()
+    }
+
+    fn bob() -> AccountId {
+        "bob.near".parse().unwrap()
+    }
+
+    fn context(signer: AccountId, deposit: u128, block_timestamp_ms: u64) -> near_sdk::VMContext {
+        VMContextBuilder::new()
+            .signer_account_id(signer.clone())
+            .predecessor_account_id(signer)
+            .attached_deposit(NearToken::from_yoctonear(deposit))
+            .block_timestamp(block_timestamp_ms * 1_000_000)
+            .build()
+    }
+
+    fn two_figure_debate(contract: &mut Contract) -> u64 {
+        testing_env!(context(owner(), 0, 1_000));
+        contract.create_debate(
+            "Topic".to_string(),
+            vec![
+                ("Fig1".to_string(), "url1".to_string()),
+                ("Fig2".to_string(), "url2".to_string()),
+            ],
+            vec![],
+            10_000,
+        )
+    }
+
+    #[test]
+    #[should_panic(expected = "You have already voted in this debate!")]
+    fn double_vote_panics() {
+        testing_env!(context(owner(), 0, 1_000));
+        let mut contract = Contract::new(owner());
+        let debate_id = two_figure_debate(&mut contract);
+
+        testing_env!(context(alice(), 100, 1_000));
+        contract.vote_debate(debate_id, 1);
+
+        testing_env!(context(alice(), 50, 1_100));
+        contract.vote_debate(debate_id, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Vote already refunded, cannot change it!")]
+    fn refund_then_change_vote_panics() {
+        testing_env!(context(owner(), 0, 1_000));
+        let mut contract = Contract::new(owner());
+        let debate_id = two_figure_debate(&mut contract);
+
+        testing_env!(context(alice(), 300, 1_000));
+        contract.vote_debate(debate_id, 1);
+
+        testing_env!(context(bob(), 100, 1_000));
+        let bob_vote_id = contract.vote_debate(debate_id, 2);
+
+        // Bob is on the losing side (100 < 300), so the refund succeeds.
+        testing_env!(context(bob(), 0, 1_100));
+        contract.claim_refund(bob_vote_id);
+
+        // Switching a refunded vote must be rejected.
+        testing_env!(context(bob(), 0, 1_100));
+        contract.change_vote(debate_id, 1);
+    }
+
+    #[test]
+    fn tied_top_figure_can_still_be_refunded() {
+        testing_env!(context(owner(), 0, 1_000));
+        let mut contract = Contract::new(owner());
+        let debate_id = two_figure_debate(&mut contract);
+
+        testing_env!(context(alice(), 200, 1_000));
+        let alice_vote_id = contract.vote_debate(debate_id, 1);
+
+        testing_env!(context(bob(), 200, 1_000));
+        contract.vote_debate(debate_id, 2);
+
+        // Both figures are tied at 200, so neither is a strict winner and a
+        // tied-top voter must still be able to reclaim their stake.
+        testing_env!(context(alice(), 0, 1_100));
+        contract.claim_refund(alice_vote_id);
+
+        let vote = contract.votes.get(&alice_vote_id).unwrap();
+        assert!(vote.claimed);
+    }
+
+    #[test]
+    fn vote_finalize_claim_winnings_pays_pro_rata_share() {
+        testing_env!(context(owner(), 0, 1_000));
+        let mut contract = Contract::new(owner());
+        let debate_id = two_figure_debate(&mut contract);
+
+        testing_env!(context(alice(), 300, 1_000));
+        let alice_vote_id = contract.vote_debate(debate_id, 1);
+
+        testing_env!(context(bob(), 100, 1_000));
+        contract.vote_debate(debate_id, 2);
+
+        // Move past the voting window and finalize.
+        testing_env!(context(owner(), 0, 12_000));
+        contract.finalize_debate(debate_id);
+
+        let debate = contract.debates.get(&debate_id).unwrap();
+        assert_eq!(debate.winner, Some(1));
+
+        testing_env!(context(alice(), 0, 12_000));
+        contract.claim_winnings(alice_vote_id);
+
+        let vote = contract.votes.get(&alice_vote_id).unwrap();
+        assert!(vote.claimed);
+
+        // Alice holds the entire winning stake, so she collects her 300
+        // back plus the whole 100 losing pool.
+        let payout = contract.winnings_payout(&debate, &vote);
+        assert_eq!(payout, 400);
     }
 }